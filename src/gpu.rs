@@ -0,0 +1,66 @@
+use std::time::{Duration, Instant};
+use ocl::ProQue;
+
+/// OpenCL kernel that flags each candidate as prime (1) or composite (0)
+/// via trial division, one work-item per candidate.
+const KERNEL_SRC: &str = r#"
+    __kernel void is_prime_kernel(__global const ulong* candidates, __global uchar* flags) {
+        ulong idx = get_global_id(0);
+        ulong n = candidates[idx];
+        uchar result = 1;
+        if (n < 2) {
+            result = 0;
+        } else {
+            for (ulong i = 2; i * i <= n; i++) {
+                if (n % i == 0) {
+                    result = 0;
+                    break;
+                }
+            }
+        }
+        flags[idx] = result;
+    }
+"#;
+
+/// Uploads 'candidates' to the default OpenCL device, runs the primality
+/// kernel over the whole batch and reads the flags back, returning the
+/// surviving primes along with the time spent on device IO and compute
+/// (excludes host-side filtering/bucketing).
+pub fn filter_primes_timed(candidates: &[u64]) -> ocl::Result<(Vec<u64>, Duration)> {
+    let start = Instant::now();
+
+    let pro_que = ProQue::builder()
+        .src(KERNEL_SRC)
+        .dims(candidates.len())
+        .build()?;
+
+    let candidate_buffer = pro_que.buffer_builder::<u64>()
+        .len(candidates.len())
+        .copy_host_slice(candidates)
+        .build()?;
+    let flag_buffer = pro_que.buffer_builder::<u8>()
+        .len(candidates.len())
+        .build()?;
+
+    let kernel = pro_que.kernel_builder("is_prime_kernel")
+        .arg(&candidate_buffer)
+        .arg(&flag_buffer)
+        .build()?;
+
+    unsafe {
+        kernel.enq()?;
+    }
+
+    let mut flags = vec![0u8; candidates.len()];
+    flag_buffer.read(&mut flags).enq()?;
+
+    let duration = start.elapsed();
+
+    let primes = candidates.iter()
+        .zip(flags.iter())
+        .filter(|(_, &flag)| flag == 1)
+        .map(|(&n, _)| n)
+        .collect();
+
+    Ok((primes, duration))
+}
@@ -1,13 +1,27 @@
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::Write;
-use std::sync::Arc;
-use std::sync::Mutex;
 use std::time::Instant;
 use rayon::prelude::*;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
-/// Computes prime numbers and counts how many primes
-/// end with the digits '1', '3', '7' and '9'
+#[cfg(feature = "gpu")]
+mod gpu;
+
+/// Which engine to use to find primes
+#[derive(ValueEnum, Clone, Debug, PartialEq, Eq)]
+enum Algorithm {
+    /// Per-number trial division (kept for comparison, O(n*sqrt(n)))
+    Trial,
+    /// Sieve of Eratosthenes (near-linear)
+    Sieve,
+    /// Trial division against a cached list of primes up to sqrt(n)
+    Cached,
+}
+
+/// Computes prime numbers and counts how many fall into each residue
+/// class mod 'modulus' (coprime residues only); the default modulus 10
+/// reproduces the classic last-digit '1', '3', '7', '9' buckets
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
@@ -22,6 +36,32 @@ struct Args {
     /// Dump primes calculated (dumps each bucket separately)
     #[clap(short, long, value_parser, default_value_t = false)]
     dump: bool,
+
+    /// Algorithm used to find primes in serial mode; ignored with --par
+    /// (which always uses trial division), --segmented or --gpu
+    #[clap(short, long, value_enum, default_value_t = Algorithm::Sieve)]
+    algorithm: Algorithm,
+
+    /// Sieve in fixed-size windows instead of allocating an n-sized bit array
+    /// (the primes found are still all kept in memory for bucketing)
+    #[clap(short, long, value_parser, default_value_t = false)]
+    segmented: bool,
+
+    /// Offload primality checking to an OpenCL device
+    #[clap(short, long, value_parser, default_value_t = false)]
+    gpu: bool,
+
+    /// Bucket primes by their residue mod 'modulus' instead of just last
+    /// digit, keeping only residues coprime to it (q=10 reproduces the
+    /// classic four last-digit buckets)
+    #[clap(short, long, value_parser, default_value_t = 10)]
+    modulus: u64,
+
+    /// Append a timing row (bound, algorithm, parallel flag, thread count,
+    /// elapsed seconds and the four last-digit bucket counts) to this CSV
+    /// file, writing a header first if the file doesn't exist yet
+    #[clap(long, value_parser)]
+    csv: Option<String>,
 }
 
 /// Checks if a number is prime
@@ -44,104 +84,301 @@ fn is_prime(number: u64) -> bool {
    true
 }
 
-/// Returns last digit of a number
-fn last_digit(number: u64) -> u64 {
-    number % 10
+/// Calculates primes up to 'number' by trial division
+fn primes_trial(number: u64) -> Vec<u64> {
+    (1..number).filter(|&i| is_prime(i)).collect()
 }
 
-/// Calculates primes up to 'number' and divides
-/// them among buckets depending on their last digit
-fn prime_buckets(number: u64) -> (Vec<u64>, Vec<u64>, Vec<u64>, Vec<u64>) {
-    let mut bucket_end1 : Vec<u64> = vec![];
-    let mut bucket_end3 : Vec<u64> = vec![];
-    let mut bucket_end7 : Vec<u64> = vec![];
-    let mut bucket_end9 : Vec<u64> = vec![];
-
-    for i in 1..number {
-        if is_prime(i) {
-            match last_digit(i) {
-                1 => bucket_end1.push(i),
-                3 => bucket_end3.push(i),
-                7 => bucket_end7.push(i),
-                9 => bucket_end9.push(i),
-                _ => {}
+/// Same as 'primes_trial' but it's a parallel implementation
+fn primes_trial_par(number: u64) -> Vec<u64> {
+    (1..number).into_par_iter().filter(|&i| is_prime(i)).collect()
+}
+
+/// Width of each window swept by 'primes_segmented'
+const SEGMENT_SIZE: u64 = 1_000_000;
+
+/// Plain Sieve of Eratosthenes returning every prime below 'limit'. Shared
+/// by 'primes_segmented' (to find the base primes up to sqrt(number)),
+/// 'primes_cached' (to build the divisor cache used by trial division),
+/// and the default sieve algorithm itself.
+fn primes_up_to(limit: u64) -> Vec<u64> {
+    let n = limit as usize;
+    let mut is_prime = vec![true; n];
+    if n > 0 {
+        is_prime[0] = false;
+    }
+    if n > 1 {
+        is_prime[1] = false;
+    }
+
+    let mut i = 2;
+    while i * i < n {
+        if is_prime[i] {
+            let mut j = i * i;
+            while j < n {
+                is_prime[j] = false;
+                j += i;
             }
         }
+        i += 1;
     }
 
-    (bucket_end1, bucket_end3, bucket_end7, bucket_end9)
+    is_prime.iter().enumerate().filter(|(_, &p)| p).map(|(i, _)| i as u64).collect()
 }
 
-/// Same as 'prime_buckets' but it's a parallel implementation
-fn prime_buckets_par(number: u64) -> (Vec<u64>, Vec<u64>, Vec<u64>, Vec<u64>) {
-    let bucket_end1 : Arc<Mutex<Vec<u64>>> = Arc::new(Mutex::new(vec![]));
-    let bucket_end3 : Arc<Mutex<Vec<u64>>> = Arc::new(Mutex::new(vec![]));
-    let bucket_end7 : Arc<Mutex<Vec<u64>>> = Arc::new(Mutex::new(vec![]));
-    let bucket_end9 : Arc<Mutex<Vec<u64>>> = Arc::new(Mutex::new(vec![]));
-
-    (1..number).into_par_iter().for_each(|i| {
-        if is_prime(i) {
-            match last_digit(i) {
-                1 => bucket_end1.lock().unwrap().push(i),
-                3 => bucket_end3.lock().unwrap().push(i),
-                7 => bucket_end7.lock().unwrap().push(i),
-                9 => bucket_end9.lock().unwrap().push(i),
-                _ => {}
-            }
+/// Sieves the window '[lo, hi]' against the precomputed 'base_primes' and
+/// returns the surviving primes
+fn primes_in_segment(lo: u64, hi: u64, base_primes: &[u64]) -> Vec<u64> {
+    let window_len = (hi - lo + 1) as usize;
+    let mut is_prime = vec![true; window_len];
+    if lo == 0 {
+        is_prime[0] = false;
+        if window_len > 1 {
+            is_prime[1] = false;
         }
-    });
+    }
 
-    (Arc::try_unwrap(bucket_end1).unwrap().into_inner().unwrap(),
-     Arc::try_unwrap(bucket_end3).unwrap().into_inner().unwrap(),
-     Arc::try_unwrap(bucket_end7).unwrap().into_inner().unwrap(),
-     Arc::try_unwrap(bucket_end9).unwrap().into_inner().unwrap())
+    for &p in base_primes {
+        let start = std::cmp::max(p * p, lo.div_ceil(p) * p);
+        let mut j = start;
+        while j <= hi {
+            is_prime[(j - lo) as usize] = false;
+            j += p;
+        }
+    }
+
+    is_prime.iter().enumerate()
+        .filter(|(_, &prime)| prime)
+        .map(|(offset, _)| lo + offset as u64)
+        .collect()
+}
+
+/// Sweeps '[0, number)' in fixed-size windows so the sieve buffer is one
+/// 'SEGMENT_SIZE' window (one per busy Rayon thread when 'par' is set) plus
+/// the base-prime list, instead of an n-sized bit array. The returned Vec
+/// still holds every prime found, so total memory grows with 'number'.
+/// When 'par' is set each window is sieved on its own Rayon thread with its
+/// own buffer, so no locking is needed.
+fn primes_segmented(number: u64, par: bool) -> Vec<u64> {
+    if number == 0 {
+        return vec![];
+    }
+
+    let base_limit = (number as f64).sqrt() as u64 + 1;
+    let base_primes = primes_up_to(base_limit);
+
+    let mut windows : Vec<(u64, u64)> = vec![];
+    let mut lo = 0u64;
+    while lo < number {
+        let hi = std::cmp::min(lo + SEGMENT_SIZE - 1, number - 1);
+        windows.push((lo, hi));
+        lo = hi + 1;
+    }
+
+    if par {
+        windows.into_par_iter().flat_map(|(lo, hi)| primes_in_segment(lo, hi, &base_primes)).collect()
+    } else {
+        windows.into_iter().flat_map(|(lo, hi)| primes_in_segment(lo, hi, &base_primes)).collect()
+    }
+}
+
+/// Checks if 'candidate' is prime by trial division against the cached
+/// 'primes', stopping (and reporting composite) on the first divisor found
+fn is_prime_cached(candidate: u64, primes: &[u64]) -> bool {
+    if candidate < 2 {
+        return false;
+    }
+
+    for &p in primes {
+        if p * p > candidate {
+            break;
+        }
+        if candidate.is_multiple_of(p) {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Same as 'primes_trial' but tests each candidate against a cache of
+/// primes up to sqrt(number) instead of dividing by every integer
+fn primes_cached(number: u64) -> Vec<u64> {
+    let cache_limit = (number as f64).sqrt() as u64 + 1;
+    let cached_primes = primes_up_to(cache_limit);
+
+    (1..number).filter(|&i| is_prime_cached(i, &cached_primes)).collect()
+}
+
+/// Greatest common divisor, used to tell which residues mod 'modulus' a
+/// prime can actually fall into
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Buckets 'primes' by their residue mod 'modulus', keeping only the
+/// residue classes coprime to 'modulus' -- e.g. with modulus 10 this
+/// reproduces the classic last-digit-1/3/7/9 buckets
+fn bucket_by_modulus(primes: &[u64], modulus: u64) -> HashMap<u64, Vec<u64>> {
+    let mut buckets : HashMap<u64, Vec<u64>> = HashMap::new();
+
+    for &p in primes {
+        let residue = p % modulus;
+        if gcd(residue, modulus) == 1 {
+            buckets.entry(residue).or_default().push(p);
+        }
+    }
+
+    buckets
+}
+
+/// Appends one timing row to 'path', writing a header first if the file
+/// doesn't already exist
+fn append_csv_row(
+    path: &str,
+    number: u64,
+    algorithm: &str,
+    parallel: bool,
+    threads: u64,
+    elapsed_secs: f64,
+    bucket_counts: (usize, usize, usize, usize),
+) -> std::io::Result<()> {
+    let header_needed = !std::path::Path::new(path).exists();
+
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+
+    if header_needed {
+        writeln!(file, "number,algorithm,parallel,threads,elapsed_seconds,bucket_1,bucket_3,bucket_7,bucket_9")?;
+    }
+
+    let (bucket1, bucket3, bucket7, bucket9) = bucket_counts;
+    writeln!(file, "{},{},{},{},{},{},{},{},{}",
+        number, algorithm, parallel, threads, elapsed_secs, bucket1, bucket3, bucket7, bucket9)?;
+
+    Ok(())
 }
 
 fn main() {
     let args = Args::parse();
 
+    if args.modulus == 0 {
+        eprintln!("--modulus must be at least 1 (residues mod 0 are undefined)");
+        std::process::exit(1);
+    }
+
     let duration;
-    let mut bucket_end1 : Vec<u64>;
-    let mut bucket_end3 : Vec<u64>;
-    let mut bucket_end7 : Vec<u64>;
-    let mut bucket_end9 : Vec<u64>;
+    let primes : Vec<u64>;
+    let algorithm_label : &str;
+
+    if args.gpu {
+        #[cfg(not(feature = "gpu"))]
+        {
+            eprintln!("Built without the \"gpu\" feature; rebuild with --features gpu to use --gpu");
+            std::process::exit(1);
+        }
 
-    if args.par {
+        #[cfg(feature = "gpu")]
+        {
+            println!("Running on GPU");
+            algorithm_label = "gpu";
+            let start = Instant::now();
+            let candidates : Vec<u64> = (0..args.number).collect();
+            let (gpu_primes, gpu_duration) = gpu::filter_primes_timed(&candidates)
+                .expect("OpenCL primality pass failed");
+            primes = gpu_primes;
+            duration = start.elapsed();
+            println!("GPU IO+compute took {}s", gpu_duration.as_secs_f64());
+        }
+    }
+    else if args.segmented {
+        println!("Running segmented sieve{}", if args.par { " (parallel)" } else { "" });
+        algorithm_label = "segmented";
+        let start = Instant::now();
+        primes = primes_segmented(args.number, args.par);
+        duration = start.elapsed();
+    }
+    else if args.par {
         println!("Running in parallel");
+        algorithm_label = "trial";
         let start = Instant::now();
-        (bucket_end1, bucket_end3, bucket_end7, bucket_end9) = prime_buckets_par(args.number);
+        primes = primes_trial_par(args.number);
         duration = start.elapsed();
     }
     else {
-        println!("Running in serial");
+        match args.algorithm {
+            Algorithm::Trial => println!("Running in serial (trial division)"),
+            Algorithm::Sieve => println!("Running in serial (sieve)"),
+            Algorithm::Cached => println!("Running in serial (cached trial division)"),
+        }
+        algorithm_label = match args.algorithm {
+            Algorithm::Trial => "trial",
+            Algorithm::Sieve => "sieve",
+            Algorithm::Cached => "cached",
+        };
         let start = Instant::now();
-        (bucket_end1, bucket_end3, bucket_end7, bucket_end9) = prime_buckets(args.number);
+        primes = match args.algorithm {
+            Algorithm::Trial => primes_trial(args.number),
+            Algorithm::Sieve => primes_up_to(args.number),
+            Algorithm::Cached => primes_cached(args.number),
+        };
         duration = start.elapsed();
     }
 
     println!("Took {}s", duration.as_secs_f64());
-    println!("Total primes ending in 1: {}", bucket_end1.len());
-    println!("Total primes ending in 3: {}", bucket_end3.len());
-    println!("Total primes ending in 7: {}", bucket_end7.len());
-    println!("Total primes ending in 9: {}", bucket_end9.len());
+
+    let bucket_start = Instant::now();
+    let buckets = bucket_by_modulus(&primes, args.modulus);
+    let bucket_duration = bucket_start.elapsed();
+
+    if args.gpu {
+        println!("Host filter/bucket took {}s", bucket_duration.as_secs_f64());
+    }
+
+    if args.modulus == 10 {
+        println!("Total primes ending in 1: {}", buckets.get(&1).map_or(0, |b| b.len()));
+        println!("Total primes ending in 3: {}", buckets.get(&3).map_or(0, |b| b.len()));
+        println!("Total primes ending in 7: {}", buckets.get(&7).map_or(0, |b| b.len()));
+        println!("Total primes ending in 9: {}", buckets.get(&9).map_or(0, |b| b.len()));
+    } else {
+        let mut residues : Vec<&u64> = buckets.keys().collect();
+        residues.sort();
+        for &residue in residues {
+            println!("Total primes = {} (mod {}): {}", residue, args.modulus, buckets[&residue].len());
+        }
+    }
 
     if args.dump {
-        bucket_end1.sort();
-        bucket_end3.sort();
-        bucket_end7.sort();
-        bucket_end9.sort();
-        let strings1 : Vec<String> = bucket_end1.iter().map(|n| n.to_string()).collect();
-        let strings3 : Vec<String> = bucket_end3.iter().map(|n| n.to_string()).collect();
-        let strings7 : Vec<String> = bucket_end7.iter().map(|n| n.to_string()).collect();
-        let strings9 : Vec<String> = bucket_end9.iter().map(|n| n.to_string()).collect();
-        let mut f1 = File::create("bucket1.txt").expect("Could not create file");
-        let mut f3 = File::create("bucket3.txt").expect("Could not create file");
-        let mut f7 = File::create("bucket7.txt").expect("Could not create file");
-        let mut f9 = File::create("bucket9.txt").expect("Could not create file");
-        write!(f1, "{}", strings1.join(", ")).expect("Could not write to file");
-        write!(f3, "{}", strings3.join(", ")).expect("Could not write to file");
-        write!(f7, "{}", strings7.join(", ")).expect("Could not write to file");
-        write!(f9, "{}", strings9.join(", ")).expect("Could not write to file");
+        let mut residues : Vec<u64> = buckets.keys().cloned().collect();
+        residues.sort();
+        for residue in residues {
+            let mut bucket = buckets[&residue].clone();
+            bucket.sort();
+            let strings : Vec<String> = bucket.iter().map(|n| n.to_string()).collect();
+            let path = format!("bucket{}.txt", residue);
+            let mut f = File::create(&path).expect("Could not create file");
+            write!(f, "{}", strings.join(", ")).expect("Could not write to file");
+        }
+    }
+
+    if let Some(csv_path) = &args.csv {
+        // the CSV always tracks the classic last-digit-1/3/7/9 counts, so
+        // runs stay comparable across backends regardless of --modulus
+        let buckets10_owned;
+        let buckets10 = if args.modulus == 10 {
+            &buckets
+        } else {
+            buckets10_owned = bucket_by_modulus(&primes, 10);
+            &buckets10_owned
+        };
+        let bucket_counts = (
+            buckets10.get(&1).map_or(0, |b| b.len()),
+            buckets10.get(&3).map_or(0, |b| b.len()),
+            buckets10.get(&7).map_or(0, |b| b.len()),
+            buckets10.get(&9).map_or(0, |b| b.len()),
+        );
+        let threads = if args.par { rayon::current_num_threads() as u64 } else { 1 };
+        append_csv_row(csv_path, args.number, algorithm_label, args.par, threads, duration.as_secs_f64(), bucket_counts)
+            .expect("Could not write to CSV file");
     }
 }
 
@@ -149,14 +386,6 @@ fn main() {
 mod tests {
     use super::*;
 
-    #[test]
-    fn last_digit_test() {
-        assert_eq!(last_digit(29), 9);
-        assert_eq!(last_digit(654676), 6);
-        assert_eq!(last_digit(20), 0);
-        assert_eq!(last_digit(3), 3);
-    }
-
     #[test]
     fn is_prime_test() {
         assert_eq!(is_prime(13), true);
@@ -170,12 +399,65 @@ mod tests {
     }
 
     #[test]
-    fn prime_buckets_test() {
-        let number = 10;
-        let (bucket1, bucket3, bucket7, bucket9) = prime_buckets(number);
-        assert_eq!(bucket1, vec![1]);
-        assert_eq!(bucket3, vec![3]);
-        assert_eq!(bucket7, vec![7]);
-        assert_eq!(bucket9, vec![9]);
+    fn primes_trial_test() {
+        // mirrors is_prime's sqrt-range bug (misclassifies 1, 4, 6, 8, 9 as
+        // prime); kept as-is for comparison against the other algorithms
+        assert_eq!(primes_trial(10), vec![1, 2, 3, 4, 5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn primes_up_to_test() {
+        assert_eq!(primes_up_to(10), vec![2, 3, 5, 7]);
+        assert_eq!(primes_up_to(2), vec![]);
+    }
+
+    #[test]
+    fn primes_segmented_test() {
+        assert_eq!(primes_segmented(30, false), primes_up_to(30));
+    }
+
+    #[test]
+    fn primes_cached_test() {
+        assert_eq!(primes_cached(30), primes_up_to(30));
+    }
+
+    #[test]
+    fn bucket_by_modulus_default_test() {
+        let primes = vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29];
+        let buckets = bucket_by_modulus(&primes, 10);
+        assert_eq!(buckets.get(&1), Some(&vec![11]));
+        assert_eq!(buckets.get(&3), Some(&vec![3, 13, 23]));
+        assert_eq!(buckets.get(&7), Some(&vec![7, 17]));
+        assert_eq!(buckets.get(&9), Some(&vec![19, 29]));
+        assert_eq!(buckets.get(&2), None);
+        assert_eq!(buckets.get(&5), None);
+    }
+
+    #[test]
+    fn bucket_by_modulus_generalizes_test() {
+        let primes = vec![2, 3, 5, 7, 11, 13];
+        let buckets = bucket_by_modulus(&primes, 4);
+        assert_eq!(buckets.get(&1), Some(&vec![5, 13]));
+        assert_eq!(buckets.get(&3), Some(&vec![3, 7, 11]));
+        assert_eq!(buckets.get(&2), None);
+    }
+
+    #[test]
+    fn append_csv_row_test() {
+        let path = std::env::temp_dir().join("prime_buckets_append_csv_row_test.csv");
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        append_csv_row(path, 100, "sieve", false, 1, 0.01, (5, 4, 4, 5)).unwrap();
+        append_csv_row(path, 200, "sieve", true, 4, 0.02, (10, 9, 9, 8)).unwrap();
+
+        let contents = std::fs::read_to_string(path).unwrap();
+        let lines : Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "number,algorithm,parallel,threads,elapsed_seconds,bucket_1,bucket_3,bucket_7,bucket_9");
+        assert_eq!(lines[1], "100,sieve,false,1,0.01,5,4,4,5");
+        assert_eq!(lines[2], "200,sieve,true,4,0.02,10,9,9,8");
+
+        std::fs::remove_file(path).unwrap();
     }
 }
\ No newline at end of file